@@ -1,18 +1,59 @@
 #[macro_use]
 extern crate vst;
 
-use vst::api::{Events, Supported};
+use vst::api::{Events, Supported, TimeInfoFlags};
 use vst::buffer::AudioBuffer;
 use vst::event::Event;
+use vst::host::Host;
 use vst::plugin::{Category, Info, Plugin, PluginParameters};
 use vst::util::AtomicFloat;
 use vst::prelude::HostCallback;
 
 
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::f32::consts::PI;
 use rand::Rng;
 
+// Number of voices available for simultaneous notes.
+const NUM_VOICES: usize = 16;
+
+// Shared sine/cosine wavetable so the sine oscillator and the LFO don't each
+// pay for a transcendental call per sample at 16-voice polyphony. One guard
+// sample past the end lets the interpolation read `i + 1` without wrapping.
+const WAVETABLE_SIZE: usize = 512;
+
+static SINE_TABLE: OnceLock<[f32; WAVETABLE_SIZE + 1]> = OnceLock::new();
+
+fn sine_table() -> &'static [f32; WAVETABLE_SIZE + 1] {
+    SINE_TABLE.get_or_init(|| {
+        let mut table = [0.0; WAVETABLE_SIZE + 1];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = (i as f32 * 2.0 * PI / WAVETABLE_SIZE as f32).cos();
+        }
+        table
+    })
+}
+
+// `phase` is in turns (0..1). Linearly interpolates between adjacent table
+// entries.
+fn fast_cos(phase: f32) -> f32 {
+    let table = sine_table();
+    let p = phase.rem_euclid(1.0) * WAVETABLE_SIZE as f32;
+    // `rem_euclid` on a tiny negative phase can round up to exactly 1.0 in
+    // f32, pushing `p` to WAVETABLE_SIZE; compute `frac` before wrapping the
+    // index so `i + 1` always stays within the guard sample instead of
+    // panicking.
+    let i_raw = p as usize;
+    let frac = p - i_raw as f32;
+    let i = i_raw % WAVETABLE_SIZE;
+    table[i] + (table[i + 1] - table[i]) * frac
+}
+
+fn fast_sin(phase: f32) -> f32 {
+    fast_cos(phase - 0.25)
+}
+
 // Oscillator type enum
 #[derive(Clone, Copy)]
 enum OscType {
@@ -21,8 +62,10 @@ enum OscType {
     Square,
     Triangle,
     Noise,
+    Vps,
 }
 
+#[derive(PartialEq)]
 enum EnvelopeStage {
     Idle,
     Attack,
@@ -31,11 +74,38 @@ enum EnvelopeStage {
     Release,
 }
 
+// Band-limiting correction applied around a discontinuity at phase `t`,
+// with `dt` the phase increment per sample (i.e. freq / sample_rate).
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+// Warps a linear phase into the VPS pivot shape: a ramp from 0 to `v` up to
+// the pivot `d`, then a ramp from `v` to 1. Sweeping `d` moves a
+// formant peak; sweeping `v` morphs sine-like into buzzy.
+fn vps_warp(phase: f32, d: f32, v: f32) -> f32 {
+    if phase < d {
+        (v / d) * phase
+    } else {
+        v + (phase - d) * ((1.0 - v) / (1.0 - d))
+    }
+}
+
 // Oscillator struct
 struct Oscillator {
     phase: f32,
     freq: f32,
     osc_type: OscType,
+    vps_d: f32,
+    vps_v: f32,
 }
 
 impl Oscillator {
@@ -44,15 +114,29 @@ impl Oscillator {
             phase: 0.0,
             freq: 440.0,
             osc_type,
+            vps_d: 0.5,
+            vps_v: 0.5,
         }
     }
 
-    fn generate_sample(&mut self, sample_rate: f32) -> f32 {
+    fn generate_sample(&mut self, sample_rate: f32, high_quality: bool) -> f32 {
+        let dt = self.freq / sample_rate;
         let output = match self.osc_type {
-            OscType::Sine => (self.phase * 2.0 * PI).sin(),
-            OscType::Saw => 1.0 - (2.0 * self.phase),
-            OscType::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            OscType::Sine => if high_quality {
+                (self.phase * 2.0 * PI).sin()
+            } else {
+                fast_sin(self.phase)
+            },
+            OscType::Saw => (2.0 * self.phase - 1.0) - poly_blep(self.phase, dt),
+            OscType::Square => {
+                let naive = if self.phase < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(self.phase, dt) - poly_blep((self.phase + 0.5) % 1.0, dt)
+            },
             OscType::Triangle => {
+                // Naive (non-band-limited) ramp. Unlike Saw/Square, triangle's
+                // aliasing is mild enough in practice that we don't integrate a
+                // PolyBLEP square here, which would need extra integrator state
+                // this oscillator doesn't otherwise carry.
                 if self.phase < 0.5 {
                     4.0 * self.phase - 1.0
                 } else {
@@ -60,6 +144,15 @@ impl Oscillator {
                 }
             },
             OscType::Noise => rand::thread_rng().gen_range(-1.0..1.0),
+            OscType::Vps => {
+                let d = self.vps_d.clamp(0.001, 0.999);
+                let v = self.vps_v;
+                // 2x oversampled to curb aliasing from the warp discontinuities.
+                let phase_b = (self.phase + dt * 0.5).rem_euclid(1.0);
+                let a = (2.0 * PI * vps_warp(self.phase, d, v)).sin();
+                let b = (2.0 * PI * vps_warp(phase_b, d, v)).sin();
+                (a + b) * 0.5
+            },
         };
 
         self.phase += self.freq / sample_rate;
@@ -69,7 +162,7 @@ impl Oscillator {
 
         output
     }
-}    
+}
 
 // ADSR envelope
 struct Envelope {
@@ -104,7 +197,7 @@ impl Envelope {
     }
 
     fn s_stage(&mut self) {
-        self.stage = EnvelopeStage::Decay;
+        self.stage = EnvelopeStage::Sustain;
     }
 
     fn r_stage(&mut self) {
@@ -130,26 +223,44 @@ impl Envelope {
         self.release = new_value;
         new_value
     }
+    // Exponential segments, like hardware FM chips use: each stage eases
+    // toward a target ("base") that overshoots (attack) or undershoots
+    // (decay/release) the real destination, which is what gives the curve
+    // its convex/concave shape instead of a straight ramp.
+    fn stage_coef(time: f32, sample_rate: f32, target_ratio: f32) -> f32 {
+        let time = time.max(1.0 / sample_rate);
+        (-((1.0 + target_ratio) / target_ratio).ln() / (time * sample_rate)).exp()
+    }
+
     fn process(&mut self) -> f32 {
+        const ATTACK_RATIO: f32 = 0.3;
+        const DECAY_RELEASE_RATIO: f32 = 0.0001;
+
         match self.stage {
             EnvelopeStage::Idle => self.level = 0.0,
             EnvelopeStage::Attack => {
-                self.level += 1.0 / (self.attack * self.sample_rate);
+                let coef = Self::stage_coef(self.attack, self.sample_rate, ATTACK_RATIO);
+                let base = 1.0 + ATTACK_RATIO;
+                self.level = base + coef * (self.level - base);
                 if self.level >= 1.0 {
                     self.level = 1.0;
-                    self.stage = EnvelopeStage::Decay;
+                    self.d_stage();
                 }
             }
             EnvelopeStage::Decay => {
-                self.level -= (1.0 - self.sustain) / (self.decay * self.sample_rate);
+                let coef = Self::stage_coef(self.decay, self.sample_rate, DECAY_RELEASE_RATIO);
+                let base = self.sustain - DECAY_RELEASE_RATIO * (1.0 - self.sustain);
+                self.level = base + coef * (self.level - base);
                 if self.level <= self.sustain {
                     self.level = self.sustain;
-                    self.stage = EnvelopeStage::Sustain;
+                    self.s_stage();
                 }
             }
             EnvelopeStage::Sustain => (),
             EnvelopeStage::Release => {
-                self.level -= self.level / (self.release * self.sample_rate);
+                let coef = Self::stage_coef(self.release, self.sample_rate, DECAY_RELEASE_RATIO);
+                let base = -DECAY_RELEASE_RATIO;
+                self.level = base + coef * (self.level - base);
                 if self.level <= 0.001 {
                     self.level = 0.0;
                     self.stage = EnvelopeStage::Idle;
@@ -160,34 +271,84 @@ impl Envelope {
     }
 }
 
-// Simple low-pass filter
-struct LowPassFilter {
+// Filter mode selectable on the biquad below. The shared "Pass" suffix is
+// standard DSP terminology here, not repeated boilerplate.
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy)]
+enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+}
+
+// Multimode biquad (transposed Direct Form II) with a real resonance/Q
+// control, replacing the old resonance-free one-pole design.
+struct Biquad {
+    filter_type: FilterType,
     cutoff: f32,
     resonance: f32,
-    y1: f32,
-    y2: f32,
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    s1: f32,
+    s2: f32,
 }
 
-impl LowPassFilter {
-    fn new() -> LowPassFilter {
-        LowPassFilter {
+impl Biquad {
+    fn new() -> Biquad {
+        Biquad {
+            filter_type: FilterType::LowPass,
             cutoff: 1000.0,
             resonance: 0.5,
-            y1: 0.0,
-            y2: 0.0,
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            s1: 0.0,
+            s2: 0.0,
+        }
+    }
+
+    // Butterworth-derived coefficients, with resonance mapped to Q so that
+    // filter_resonance actually shapes the response instead of being ignored.
+    fn update_coefficients(&mut self, sample_rate: f32) {
+        let f = (PI * self.cutoff / sample_rate).tan();
+        let q = 0.5 + self.resonance * 9.5;
+        let f2 = f * f;
+        let a0r = 1.0 / (1.0 + f / q + f2);
+
+        match self.filter_type {
+            FilterType::LowPass => {
+                self.b0 = f2 * a0r;
+                self.b1 = 2.0 * self.b0;
+                self.b2 = self.b0;
+            }
+            FilterType::HighPass => {
+                self.b0 = a0r;
+                self.b1 = -2.0 * self.b0;
+                self.b2 = self.b0;
+            }
+            FilterType::BandPass => {
+                self.b0 = (f / q) * a0r;
+                self.b1 = 0.0;
+                self.b2 = -self.b0;
+            }
         }
+        self.a1 = (2.0 * f2 - 2.0) * a0r;
+        self.a2 = (1.0 - f / q + f2) * a0r;
     }
 
     fn process(&mut self, input: f32, sample_rate: f32) -> f32 {
-        let c = 2.0 * PI * self.cutoff / sample_rate;
-        let _r = 1.0 / (2.0 * (1.0 - self.resonance));
-        let k = c / (1.0 + c);
+        self.update_coefficients(sample_rate);
 
-        let output = input * k + self.y1 * (1.0 - k);
-        self.y1 = output * k + self.y2 * (1.0 - k);
-        self.y2 = output;
+        let y = self.b0 * input + self.s1;
+        self.s1 = self.b1 * input - self.a1 * y + self.s2;
+        self.s2 = self.b2 * input - self.a2 * y;
 
-        output
+        y
     }
 }
 
@@ -205,8 +366,12 @@ impl LFO {
         }
     }
 
-    fn process(&mut self, sample_rate: f32) -> f32 {
-        let output = (self.phase * 2.0 * PI).sin();
+    fn process(&mut self, sample_rate: f32, high_quality: bool) -> f32 {
+        let output = if high_quality {
+            (self.phase * 2.0 * PI).sin()
+        } else {
+            fast_sin(self.phase)
+        };
         self.phase += self.freq / sample_rate;
         if self.phase >= 1.0 {
             self.phase -= 1.0;
@@ -215,14 +380,295 @@ impl LFO {
     }
 }
 
+// One FM operator: a sine phase accumulator with its own envelope, running
+// at a multiple of the voice's note frequency.
+struct FmOperator {
+    phase: f32,
+    multiplier: f32,
+    output_level: f32,
+    envelope: Envelope,
+}
+
+impl FmOperator {
+    fn new(sample_rate: f32, multiplier: f32, output_level: f32) -> FmOperator {
+        FmOperator {
+            phase: 0.0,
+            multiplier,
+            output_level,
+            envelope: Envelope::new(sample_rate),
+        }
+    }
+
+    fn advance_phase(&mut self, note_freq: f32, sample_rate: f32) {
+        self.phase += (note_freq * self.multiplier) / sample_rate;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+    }
+}
+
+// Routing table for the four operators, YM2612-style: which operators
+// modulate which, and which are summed to produce the audible output.
+#[derive(Clone, Copy)]
+enum FmAlgorithm {
+    SerialStack,       // op4 -> op3 -> op2 -> op1 (carrier)
+    TwoParallelStacks, // (op2 -> op1) + (op4 -> op3), both carriers
+    OneModulatesThree, // op4 modulates op1, op2 and op3 (all carriers)
+    AllParallel,       // all four operators are carriers, no modulation
+}
+
+// The FM synthesis path for a single voice: four operators plus the
+// algorithm wiring them together and operator 1's feedback loop.
+struct FmVoice {
+    operators: [FmOperator; 4],
+    algorithm: FmAlgorithm,
+    feedback: f32,
+    fb_history: [f32; 2],
+}
+
+impl FmVoice {
+    fn new(sample_rate: f32) -> FmVoice {
+        FmVoice {
+            operators: [
+                FmOperator::new(sample_rate, 1.0, 1.0),
+                FmOperator::new(sample_rate, 1.0, 1.0),
+                FmOperator::new(sample_rate, 1.0, 1.0),
+                FmOperator::new(sample_rate, 1.0, 1.0),
+            ],
+            algorithm: FmAlgorithm::SerialStack,
+            feedback: 0.0,
+            fb_history: [0.0, 0.0],
+        }
+    }
+
+    fn trigger(&mut self) {
+        for op in &mut self.operators {
+            op.envelope.trigger();
+        }
+        self.fb_history = [0.0, 0.0];
+    }
+
+    fn release(&mut self) {
+        for op in &mut self.operators {
+            op.envelope.r_stage();
+        }
+    }
+
+    fn process(&mut self, note_freq: f32, sample_rate: f32) -> f32 {
+        let env_level: [f32; 4] = std::array::from_fn(|i| self.operators[i].envelope.process());
+        let fb_mod = (self.fb_history[0] + self.fb_history[1]) * 0.5 * self.feedback;
+
+        let op4 = (2.0 * PI * self.operators[3].phase).sin() * env_level[3] * self.operators[3].output_level;
+
+        let op3_mod = match self.algorithm {
+            FmAlgorithm::AllParallel => 0.0,
+            _ => op4,
+        };
+        let op3 = (2.0 * PI * (self.operators[2].phase + op3_mod)).sin() * env_level[2] * self.operators[2].output_level;
+
+        let op2_mod = match self.algorithm {
+            FmAlgorithm::SerialStack => op3,
+            FmAlgorithm::OneModulatesThree => op4,
+            _ => 0.0,
+        };
+        let op2 = (2.0 * PI * (self.operators[1].phase + op2_mod)).sin() * env_level[1] * self.operators[1].output_level;
+
+        let op1_mod = match self.algorithm {
+            FmAlgorithm::SerialStack | FmAlgorithm::TwoParallelStacks => op2,
+            FmAlgorithm::OneModulatesThree => op4,
+            FmAlgorithm::AllParallel => 0.0,
+        } + fb_mod;
+        let op1 = (2.0 * PI * (self.operators[0].phase + op1_mod)).sin() * env_level[0] * self.operators[0].output_level;
+
+        let output = match self.algorithm {
+            FmAlgorithm::SerialStack => op1,
+            FmAlgorithm::TwoParallelStacks => (op1 + op3) * 0.5,
+            FmAlgorithm::OneModulatesThree => (op1 + op2 + op3) / 3.0,
+            FmAlgorithm::AllParallel => (op1 + op2 + op3 + op4) * 0.25,
+        };
+
+        self.fb_history[1] = self.fb_history[0];
+        self.fb_history[0] = op1;
+
+        for op in &mut self.operators {
+            op.advance_phase(note_freq, sample_rate);
+        }
+
+        output
+    }
+}
+
+// A single polyphonic voice: its own oscillator bank, envelope and filter so
+// that notes no longer steal each other and releases get a proper tail.
+struct Voice {
+    oscillators: [Oscillator; 6],
+    fm: FmVoice,
+    envelope: Envelope,
+    filter: Biquad,
+    note: u8,
+    note_freq: f32,
+    velocity: u8,
+    active: bool,
+}
+
+impl Voice {
+    fn new(sample_rate: f32) -> Voice {
+        Voice {
+            oscillators: [
+                Oscillator::new(OscType::Sine),
+                Oscillator::new(OscType::Saw),
+                Oscillator::new(OscType::Square),
+                Oscillator::new(OscType::Triangle),
+                Oscillator::new(OscType::Noise),
+                Oscillator::new(OscType::Vps),
+            ],
+            fm: FmVoice::new(sample_rate),
+            envelope: Envelope::new(sample_rate),
+            filter: Biquad::new(),
+            note: 0,
+            note_freq: 440.0,
+            velocity: 0,
+            active: false,
+        }
+    }
+
+    fn start(&mut self, note: u8, velocity: u8) {
+        let freq = midi_pitch_to_freq(note);
+        self.oscillators[0].freq = freq;
+        self.oscillators[1].freq = freq * 1.01; // Slight detuning for second oscillator
+        self.note = note;
+        self.note_freq = freq;
+        self.velocity = velocity;
+        self.envelope.trigger();
+        self.fm.trigger();
+        self.active = true;
+    }
+
+    fn release(&mut self) {
+        self.envelope.r_stage();
+        self.fm.release();
+    }
+
+    fn process(&mut self, sample_rate: f32, lfo_value: f32, params: &SynthParams) -> f32 {
+        self.envelope.mod_atk(params.env_attack.get());
+        self.envelope.mod_dec(params.env_decay.get());
+        self.envelope.mod_sus(params.env_sustain.get());
+        self.envelope.mod_rel(params.env_release.get());
+
+        let mut output = if params.fm_enabled.get() >= 0.5 {
+            self.fm.algorithm = params.fm_algorithm();
+            self.fm.feedback = params.fm_feedback.get();
+            for (op, (mult, level)) in self.fm.operators.iter_mut().zip(params.fm_operator_settings()) {
+                op.multiplier = mult;
+                op.output_level = level;
+                op.envelope.mod_atk(params.env_attack.get());
+                op.envelope.mod_dec(params.env_decay.get());
+                op.envelope.mod_sus(params.env_sustain.get());
+                op.envelope.mod_rel(params.env_release.get());
+            }
+            self.fm.process(self.note_freq, sample_rate)
+        } else {
+            let high_quality = params.high_quality.get() >= 0.5;
+            let mut additive = 0.0;
+            for osc in &mut self.oscillators {
+                osc.vps_d = params.vps_d.get();
+                osc.vps_v = params.vps_v.get();
+                additive += osc.generate_sample(sample_rate, high_quality);
+            }
+            additive * 0.5 // Mix oscillators
+        };
+
+        output *= self.envelope.process();
+
+        let cutoff_mod = params.filter_cutoff.get() * (1.0 + lfo_value * params.lfo_amount.get());
+        self.filter.cutoff = cutoff_mod.clamp(20.0, 20000.0);
+        self.filter.resonance = params.filter_resonance.get();
+        self.filter.filter_type = params.filter_type();
+
+        output = self.filter.process(output, sample_rate);
+
+        if self.envelope.stage == EnvelopeStage::Idle {
+            self.active = false;
+        }
+
+        output
+    }
+}
+
+// One step of a pattern: a semitone offset from the held root note, or a
+// rest.
+#[derive(Clone, Copy)]
+struct Step {
+    semitone_offset: i8,
+    rest: bool,
+}
+
+impl Step {
+    fn note(offset: i8) -> Step {
+        Step { semitone_offset: offset, rest: false }
+    }
+
+    fn rest() -> Step {
+        Step { semitone_offset: 0, rest: true }
+    }
+}
+
+// Up to 16 steps played relative to whatever note is currently held.
+struct Sequence {
+    steps: [Step; 16],
+    num_steps: usize,
+}
+
+impl Sequence {
+    fn new() -> Sequence {
+        Sequence {
+            steps: [
+                Step::note(0), Step::note(12), Step::note(7), Step::rest(),
+                Step::note(0), Step::note(15), Step::note(7), Step::rest(),
+                Step::note(0), Step::note(12), Step::note(7), Step::note(10),
+                Step::note(0), Step::note(15), Step::note(7), Step::rest(),
+            ],
+            num_steps: 16,
+        }
+    }
+}
+
+// Tempo-synced driver that fires Note On/Note Off through the same voice
+// machinery `process_events` uses, turning one held key into a pattern.
+struct Sequencer {
+    sequence: Sequence,
+    root_note: Option<u8>,
+    sounding_note: Option<u8>,
+    current_step: usize,
+    samples_into_step: f32,
+    step_length_samples: f32,
+}
+
+impl Sequencer {
+    fn new() -> Sequencer {
+        Sequencer {
+            sequence: Sequence::new(),
+            root_note: None,
+            sounding_note: None,
+            current_step: 0,
+            samples_into_step: 0.0,
+            step_length_samples: 0.0,
+        }
+    }
+}
+
+fn current_tempo(host: &HostCallback) -> f64 {
+    host.get_time_info(TimeInfoFlags::TEMPO_VALID.bits())
+        .map(|info| info.tempo)
+        .unwrap_or(120.0)
+}
+
 struct MySynth {
     sample_rate: f32,
-    oscillators: [Oscillator; 5],
-    envelope: Envelope,
-    filter: LowPassFilter,
+    voices: Vec<Voice>,
     lfo: LFO,
-    note: u8,
-    note_on: bool,
+    sequencer: Sequencer,
+    host: HostCallback,
     params: Arc<SynthParams>,
 }
 
@@ -233,24 +679,72 @@ struct SynthParams {
     filter_resonance: AtomicFloat,
     lfo_freq: AtomicFloat,
     lfo_amount: AtomicFloat,
+    fm_enabled: AtomicFloat,
+    fm_algorithm: AtomicFloat,
+    fm_feedback: AtomicFloat,
+    op1_multiplier: AtomicFloat,
+    op1_level: AtomicFloat,
+    op2_multiplier: AtomicFloat,
+    op2_level: AtomicFloat,
+    op3_multiplier: AtomicFloat,
+    op3_level: AtomicFloat,
+    op4_multiplier: AtomicFloat,
+    op4_level: AtomicFloat,
+    filter_type: AtomicFloat,
+    high_quality: AtomicFloat,
+    seq_enabled: AtomicFloat,
+    seq_rate: AtomicFloat,
+    seq_gate: AtomicFloat,
+    env_attack: AtomicFloat,
+    env_decay: AtomicFloat,
+    env_sustain: AtomicFloat,
+    env_release: AtomicFloat,
+    vps_d: AtomicFloat,
+    vps_v: AtomicFloat,
+}
+
+impl SynthParams {
+    fn fm_algorithm(&self) -> FmAlgorithm {
+        match (self.fm_algorithm.get() * 4.0) as i32 {
+            0 => FmAlgorithm::SerialStack,
+            1 => FmAlgorithm::TwoParallelStacks,
+            2 => FmAlgorithm::OneModulatesThree,
+            _ => FmAlgorithm::AllParallel,
+        }
+    }
+
+    fn filter_type(&self) -> FilterType {
+        match (self.filter_type.get() * 3.0) as i32 {
+            0 => FilterType::LowPass,
+            1 => FilterType::HighPass,
+            _ => FilterType::BandPass,
+        }
+    }
+
+    fn fm_operator_settings(&self) -> [(f32, f32); 4] {
+        [
+            (self.op1_multiplier.get(), self.op1_level.get()),
+            (self.op2_multiplier.get(), self.op2_level.get()),
+            (self.op3_multiplier.get(), self.op3_level.get()),
+            (self.op4_multiplier.get(), self.op4_level.get()),
+        ]
+    }
+
+    // 1 to 8 steps per quarter note.
+    fn seq_steps_per_quarter(&self) -> f32 {
+        1.0 + self.seq_rate.get() * 7.0
+    }
 }
 
 impl Default for MySynth {
     fn default() -> Self {
+        let sample_rate = 44100.0;
         MySynth {
-            sample_rate: 44100.0,
-            oscillators: [
-                Oscillator::new(OscType::Sine),
-                Oscillator::new(OscType::Saw),
-                Oscillator::new(OscType::Square),
-                Oscillator::new(OscType::Triangle),
-                Oscillator::new(OscType::Noise),
-            ],
-            envelope: Envelope::new(44100.0),
-            filter: LowPassFilter::new(),
+            sample_rate,
+            voices: (0..NUM_VOICES).map(|_| Voice::new(sample_rate)).collect(),
             lfo: LFO::new(),
-            note: 0,
-            note_on: false,
+            sequencer: Sequencer::new(),
+            host: HostCallback::default(),
             params: Arc::new(SynthParams {
                 osc1_freq: AtomicFloat::new(440.0),
                 osc2_freq: AtomicFloat::new(440.0),
@@ -258,14 +752,97 @@ impl Default for MySynth {
                 filter_resonance: AtomicFloat::new(0.5),
                 lfo_freq: AtomicFloat::new(1.0),
                 lfo_amount: AtomicFloat::new(0.5),
+                fm_enabled: AtomicFloat::new(0.0),
+                fm_algorithm: AtomicFloat::new(0.0),
+                fm_feedback: AtomicFloat::new(0.0),
+                op1_multiplier: AtomicFloat::new(1.0),
+                op1_level: AtomicFloat::new(1.0),
+                op2_multiplier: AtomicFloat::new(1.0),
+                op2_level: AtomicFloat::new(1.0),
+                op3_multiplier: AtomicFloat::new(1.0),
+                op3_level: AtomicFloat::new(1.0),
+                op4_multiplier: AtomicFloat::new(1.0),
+                op4_level: AtomicFloat::new(1.0),
+                filter_type: AtomicFloat::new(0.0),
+                high_quality: AtomicFloat::new(0.0),
+                seq_enabled: AtomicFloat::new(0.0),
+                seq_rate: AtomicFloat::new(0.0),
+                seq_gate: AtomicFloat::new(0.5),
+                env_attack: AtomicFloat::new(0.01),
+                env_decay: AtomicFloat::new(0.1),
+                env_sustain: AtomicFloat::new(0.5),
+                env_release: AtomicFloat::new(0.2),
+                vps_d: AtomicFloat::new(0.5),
+                vps_v: AtomicFloat::new(0.5),
             }),
         }
     }
 }
 
+impl MySynth {
+    // Find a free voice, or steal the active voice whose envelope is
+    // quietest (typically the oldest one fading out).
+    fn allocate_voice(&mut self) -> &mut Voice {
+        if let Some(idx) = self.voices.iter().position(|v| !v.active) {
+            return &mut self.voices[idx];
+        }
+
+        let steal_idx = self.voices
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.envelope.level.partial_cmp(&b.envelope.level).unwrap())
+            .map(|(idx, _)| idx)
+            .unwrap_or(0);
+
+        &mut self.voices[steal_idx]
+    }
+
+    fn release_sounding_step(&mut self) {
+        if let Some(note) = self.sequencer.sounding_note.take() {
+            if let Some(voice) = self.voices.iter_mut().find(|v| v.active && v.note == note) {
+                voice.release();
+            }
+        }
+    }
+
+    // Advance the sequencer by one sample, firing steps through the same
+    // voice machinery `process_events` uses.
+    fn advance_sequencer(&mut self) {
+        if self.sequencer.root_note.is_some() {
+            // Clamp strictly below 1.0 so the gate threshold is always
+            // crossed before the next step boundary resets the counter;
+            // at a gate of 1.0 the prior note would only be released at the
+            // boundary, in the same sample as the next step's Note On.
+            let gate_samples = self.sequencer.step_length_samples * self.params.seq_gate.get().clamp(0.01, 0.99);
+            if self.sequencer.samples_into_step >= gate_samples {
+                self.release_sounding_step();
+            }
+
+            self.sequencer.samples_into_step += 1.0;
+            if self.sequencer.samples_into_step >= self.sequencer.step_length_samples {
+                self.sequencer.samples_into_step = 0.0;
+                self.release_sounding_step();
+
+                let root = self.sequencer.root_note.unwrap();
+                let step = self.sequencer.sequence.steps[self.sequencer.current_step];
+                self.sequencer.current_step = (self.sequencer.current_step + 1) % self.sequencer.sequence.num_steps;
+
+                if !step.rest {
+                    let note = (root as i16 + step.semitone_offset as i16).clamp(0, 127) as u8;
+                    self.allocate_voice().start(note, 100);
+                    self.sequencer.sounding_note = Some(note);
+                }
+            }
+        }
+    }
+}
+
 impl Plugin for MySynth {
-    fn new(_host: HostCallback) -> Self {
-        MySynth::default()
+    fn new(host: HostCallback) -> Self {
+        MySynth {
+            host,
+            ..MySynth::default()
+        }
     }
 
     fn get_info(&self) -> Info {
@@ -276,7 +853,7 @@ impl Plugin for MySynth {
             category: Category::Synth,
             inputs: 0,
             outputs: 2,
-            parameters: 6,
+            parameters: 28,
             ..Default::default()
         }
     }
@@ -290,23 +867,27 @@ impl Plugin for MySynth {
         let (_, mut outputs) = buffer.split();
         let output_count = outputs.len();
 
+        let seq_enabled = self.params.seq_enabled.get() >= 0.5;
+        if seq_enabled {
+            let tempo = current_tempo(&self.host);
+            let quarter_note_length = self.sample_rate as f64 * 60.0 / tempo;
+            self.sequencer.step_length_samples =
+                (quarter_note_length / self.params.seq_steps_per_quarter() as f64) as f32;
+        }
+
         for sample_idx in 0..samples {
             let mut output = 0.0;
+            let high_quality = self.params.high_quality.get() >= 0.5;
+            let lfo_value = self.lfo.process(self.sample_rate, high_quality);
 
-            if self.note_on {
-                for osc in &mut self.oscillators {
-                    output += osc.generate_sample(self.sample_rate);
-                }
-                output *= 0.5; // Mix oscillators
-
-                output *= self.envelope.process();
-
-                // Apply LFO to filter cutoff
-                let lfo_value = self.lfo.process(self.sample_rate);
-                let cutoff_mod = self.params.filter_cutoff.get() * (1.0 + lfo_value * self.params.lfo_amount.get());
-                self.filter.cutoff = cutoff_mod.clamp(20.0, 20000.0);
+            if seq_enabled {
+                self.advance_sequencer();
+            }
 
-                output = self.filter.process(output, self.sample_rate);
+            for voice in &mut self.voices {
+                if voice.active {
+                    output += voice.process(self.sample_rate, lfo_value, &self.params);
+                }
             }
 
             for buf_idx in 0..output_count {
@@ -321,17 +902,30 @@ impl Plugin for MySynth {
                 Event::Midi(ev) => {
                     match ev.data[0] {
                         128 => { // Note Off
-                            if ev.data[1] == self.note {
-                                self.envelope.r_stage();
+                            let note = ev.data[1];
+                            if self.params.seq_enabled.get() >= 0.5 {
+                                if self.sequencer.root_note == Some(note) {
+                                    self.sequencer.root_note = None;
+                                    if let Some(sounding) = self.sequencer.sounding_note.take() {
+                                        if let Some(voice) = self.voices.iter_mut().find(|v| v.active && v.note == sounding) {
+                                            voice.release();
+                                        }
+                                    }
+                                }
+                            } else if let Some(voice) = self.voices.iter_mut().find(|v| v.active && v.note == note) {
+                                voice.release();
                             }
                         },
                         144 => { // Note On
-                            self.note = ev.data[1];
-                            let freq = midi_pitch_to_freq(self.note);
-                            self.oscillators[0].freq = freq;
-                            self.oscillators[1].freq = freq * 1.01; // Slight detuning for second oscillator
-                            self.envelope.trigger();
-                            self.note_on = true;
+                            let note = ev.data[1];
+                            let velocity = ev.data[2];
+                            if self.params.seq_enabled.get() >= 0.5 {
+                                self.sequencer.root_note = Some(note);
+                                self.sequencer.current_step = 0;
+                                self.sequencer.samples_into_step = 0.0;
+                            } else {
+                                self.allocate_voice().start(note, velocity);
+                            }
                         },
                         _ => (),
                     }
@@ -362,6 +956,28 @@ impl PluginParameters for SynthParams {
             3 => self.filter_resonance.get(),
             4 => self.lfo_freq.get() / 10.0,
             5 => self.lfo_amount.get(),
+            6 => self.fm_enabled.get(),
+            7 => self.fm_algorithm.get(),
+            8 => self.fm_feedback.get(),
+            9 => self.op1_multiplier.get() / 16.0,
+            10 => self.op1_level.get(),
+            11 => self.op2_multiplier.get() / 16.0,
+            12 => self.op2_level.get(),
+            13 => self.op3_multiplier.get() / 16.0,
+            14 => self.op3_level.get(),
+            15 => self.op4_multiplier.get() / 16.0,
+            16 => self.op4_level.get(),
+            17 => self.filter_type.get(),
+            18 => self.high_quality.get(),
+            19 => self.seq_enabled.get(),
+            20 => self.seq_rate.get(),
+            21 => self.seq_gate.get(),
+            22 => self.env_attack.get() / 2.0,
+            23 => self.env_decay.get() / 2.0,
+            24 => self.env_sustain.get(),
+            25 => self.env_release.get() / 2.0,
+            26 => self.vps_d.get(),
+            27 => self.vps_v.get(),
             _ => 0.0,
         }
     }
@@ -374,6 +990,28 @@ impl PluginParameters for SynthParams {
             3 => self.filter_resonance.set(value),
             4 => self.lfo_freq.set(value * 20.0),
             5 => self.lfo_amount.set(value * 2.0),
+            6 => self.fm_enabled.set(value),
+            7 => self.fm_algorithm.set(value),
+            8 => self.fm_feedback.set(value),
+            9 => self.op1_multiplier.set(value * 16.0),
+            10 => self.op1_level.set(value),
+            11 => self.op2_multiplier.set(value * 16.0),
+            12 => self.op2_level.set(value),
+            13 => self.op3_multiplier.set(value * 16.0),
+            14 => self.op3_level.set(value),
+            15 => self.op4_multiplier.set(value * 16.0),
+            16 => self.op4_level.set(value),
+            17 => self.filter_type.set(value),
+            18 => self.high_quality.set(value),
+            19 => self.seq_enabled.set(value),
+            20 => self.seq_rate.set(value),
+            21 => self.seq_gate.set(value),
+            22 => self.env_attack.set(value * 2.0),
+            23 => self.env_decay.set(value * 2.0),
+            24 => self.env_sustain.set(value),
+            25 => self.env_release.set(value * 2.0),
+            26 => self.vps_d.set(value),
+            27 => self.vps_v.set(value),
             _ => (),
         }
     }
@@ -386,6 +1024,28 @@ impl PluginParameters for SynthParams {
             3 => "Filter Resonance".to_string(),
             4 => "LFO Freq".to_string(),
             5 => "LFO Amount".to_string(),
+            6 => "FM Enabled".to_string(),
+            7 => "FM Algorithm".to_string(),
+            8 => "FM Feedback".to_string(),
+            9 => "Op1 Multiplier".to_string(),
+            10 => "Op1 Level".to_string(),
+            11 => "Op2 Multiplier".to_string(),
+            12 => "Op2 Level".to_string(),
+            13 => "Op3 Multiplier".to_string(),
+            14 => "Op3 Level".to_string(),
+            15 => "Op4 Multiplier".to_string(),
+            16 => "Op4 Level".to_string(),
+            17 => "Filter Type".to_string(),
+            18 => "High Quality".to_string(),
+            19 => "Seq On/Off".to_string(),
+            20 => "Seq Rate".to_string(),
+            21 => "Seq Gate".to_string(),
+            22 => "Attack".to_string(),
+            23 => "Decay".to_string(),
+            24 => "Sustain".to_string(),
+            25 => "Release".to_string(),
+            26 => "VPS D".to_string(),
+            27 => "VPS V".to_string(),
             _ => "".to_string(),
         }
     }